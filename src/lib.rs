@@ -1,13 +1,152 @@
-use std::{collections::HashMap, error::Error, fs};
-use serde_yaml::{Value, from_str};
+use std::{collections::{hash_map::DefaultHasher, HashMap}, env, error::Error, fmt, fs, hash::{Hash, Hasher}, io::{self, Read}, net::{IpAddr, SocketAddr}, process::Command, str::FromStr, sync::{Arc, Mutex}, time::Duration};
+use dashmap::DashMap;
+use regex::Regex;
+use serde_yaml::{Mapping, Value, from_str};
 use strfmt::strfmt;
 
+#[cfg(any(test, feature = "testing"))]
+mod test_support;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    ParseError(String),
+    FormatError(String),
+    NotFound
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "I/O error: {e}"),
+            ConfigError::ParseError(e) => write!(f, "parse error: {e}"),
+            ConfigError::FormatError(e) => write!(f, "format error: {e}"),
+            ConfigError::NotFound => write!(f, "config file not found")
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_yaml::Error> for ConfigError {
+    fn from(e: serde_yaml::Error) -> Self {
+        ConfigError::ParseError(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>
+}
+
+impl ConfigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for path in self.added.iter() {
+            writeln!(f, "+ {path}")?;
+        }
+
+        for path in self.removed.iter() {
+            writeln!(f, "- {path}")?;
+        }
+
+        for path in self.changed.iter() {
+            writeln!(f, "~ {path}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Null,
+    Bool,
+    Integer,
+    Float,
+    String,
+    Sequence,
+    Mapping
+}
+
+type AuditLog = Arc<Mutex<Vec<(String, String)>>>;
+
+pub type Migration = fn(&mut Config) -> Result<(), ConfigError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeConflictType {
+    Added,
+    Removed,
+    Changed,
+    SequenceReplaced
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeConflict {
+    pub path: String,
+    pub old_value: String,
+    pub new_value: String,
+    pub conflict_type: MergeConflictType,
+    pub label: Option<String>
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    pub label: String,
+    pub config: Config,
+    pub priority: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigNode {
+    pub key: String,
+    pub value: Option<String>,
+    pub children: Vec<ConfigNode>
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     content: Value,
     filename: String,
     separator: String,
-    environment: Option<String>
+    environment: Option<String>,
+    override_sources: HashMap<String, String>,
+    allow_process_substitution: bool,
+    audit_log: Option<AuditLog>,
+    last_checksum: Option<u64>,
+    annotations: HashMap<String, HashMap<String, String>>
+}
+
+#[derive(Debug, Clone)]
+pub struct LazyMergedConfig {
+    configs: Vec<Config>,
+    cache: DashMap<String, Option<Value>>
+}
+
+impl PartialEq for Config {
+    fn eq(&self, other: &Self) -> bool {
+        self.content == other.content
+    }
 }
 
 impl Default for Config {
@@ -20,17 +159,146 @@ impl Config {
     pub fn new(filename: &str, sep: &str, env: Option<&str>) -> Result<Config, Box<dyn Error>> {
         let (file, env) = Self::get_file(filename, env);
 
-        match Self::load(&file) {
-            Ok(yaml) => Ok(Config {
-                content: yaml,
-                filename: file,
-                separator: sep.to_string(),
-                environment: env
-            }),
-            Err(e) => Err(e)
+        // boxes the original io / serde_yaml errors rather than ConfigError
+        let yaml = fs::read_to_string(&file)?;
+        let parsed = from_str(&yaml)?;
+
+        let mut config = Self::from_content(parsed, file, sep, env);
+        config.last_checksum = Some(Self::checksum(&yaml));
+        Ok(config)
+    }
+
+    pub fn reload(&mut self) -> Result<(), ConfigError> {
+        if self.filename.is_empty() {
+            return Err(ConfigError::NotFound);
+        }
+
+        let (yaml, checksum) = Self::load_with_checksum(&self.filename)?;
+        self.content = yaml;
+        self.last_checksum = Some(checksum);
+        self.override_sources.clear();
+
+        Ok(())
+    }
+
+    pub fn reload_if_changed(&mut self) -> Result<bool, ConfigError> {
+        if self.filename.is_empty() {
+            return Err(ConfigError::NotFound);
+        }
+
+        let checksum = Self::checksum(&fs::read_to_string(&self.filename)?);
+
+        if self.last_checksum == Some(checksum) {
+            return Ok(false);
+        }
+
+        self.reload()?;
+        Ok(true)
+    }
+
+    pub fn try_new_or_empty(filename: &str, sep: &str, env: Option<&str>) -> Config {
+        match Self::new(filename, sep, env) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("trail-config: could not load '{filename}', using empty config: {e}");
+                Self::empty(sep)
+            }
+        }
+    }
+
+    pub fn load_multiple_and_merge(filenames: &[&str], sep: &str, env: Option<&str>) -> Result<Config, ConfigError> {
+        let mut merged: Option<Config> = None;
+
+        for filename in filenames.iter() {
+            let (file, env) = Self::get_file(filename, env);
+
+            let content = match Self::load(&file) {
+                Ok(v) => v,
+                Err(ConfigError::Io(e)) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e)
+            };
+
+            merged.get_or_insert_with(|| Self::from_content(Value::Mapping(Mapping::new()), String::new(), sep, env))
+                .merge_overlay(&content, &format!("'{file}'"))?;
+        }
+
+        merged.ok_or(ConfigError::NotFound)
+    }
+
+    pub fn merge_all_in_dir(dir: &str, extension: &str, sep: &str) -> Result<Config, ConfigError> {
+        let extension = extension.trim_start_matches('.');
+        let mut files = vec![];
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.is_file() && path.extension().map(|e| e == extension).unwrap_or(false) {
+                files.push(path);
+            }
+        }
+
+        files.sort_by_cached_key(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let digits = name.chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+            let prefix = digits.parse::<u64>().ok();
+
+            (prefix.is_none(), prefix, name)
+        });
+
+        let mut merged = Self::empty(sep);
+
+        for path in files.iter() {
+            let file = path.to_string_lossy();
+            let content = Self::load(&file)?;
+            merged.merge_overlay(&content, &format!("'{file}'"))?;
+        }
+
+        Ok(merged)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R, sep: &str) -> Result<Config, ConfigError> {
+        let mut yaml = String::new();
+        reader.read_to_string(&mut yaml)?;
+        let parsed = from_str(&yaml)?;
+
+        Ok(Self::from_content(parsed, String::new(), sep, None))
+    }
+
+    pub fn from_stdin(sep: &str) -> Result<Config, ConfigError> {
+        Self::from_reader(io::stdin().lock(), sep)
+    }
+
+    pub fn allow_process_substitution(mut self, allow: bool) -> Self {
+        self.allow_process_substitution = allow;
+        self
+    }
+
+    pub fn with_audit_log(mut self) -> Self {
+        self.audit_log = Some(Arc::new(Mutex::new(vec![])));
+        self
+    }
+
+    pub fn get_audit_log(&self) -> Vec<(String, String)> {
+        match self.audit_log.as_ref().and_then(|log| log.lock().ok()) {
+            Some(log) => log.clone(),
+            None => vec![]
+        }
+    }
+
+    pub fn clear_audit_log(&self) {
+        if let Some(mut log) = self.audit_log.as_ref().and_then(|log| log.lock().ok()) {
+            log.clear();
         }
     }
 
+    pub fn annotate(&mut self, path: &str, metadata: HashMap<String, String>) {
+        self.annotations.entry(path.to_string()).or_default().extend(metadata);
+    }
+
+    pub fn get_annotation(&self, path: &str, key: &str) -> Option<&str> {
+        self.annotations.get(path)?.get(key).map(|v| v.as_str())
+    }
+
     pub fn environment(&self) -> Option<&str> {
         match &self.environment {
             Some(v) => Some(v),
@@ -39,11 +307,64 @@ impl Config {
     }
 
     pub fn get(&self, path: &str) -> Option<Value> {
-        Self::get_leaf(&self.content, path, &self.separator)
+        let value = Self::get_leaf(&self.content, path, &self.separator);
+        self.record(path, value.as_ref());
+
+        value
+    }
+
+    pub fn get_or_compute<F: FnOnce() -> Value>(&self, path: &str, compute: F) -> Value {
+        match self.get(path) {
+            Some(v) => v,
+            None => compute()
+        }
+    }
+
+    pub fn get_all(&self, path: &str) -> Vec<(String, Value)> {
+        let pattern = path.split(&self.separator).collect::<Vec<&str>>();
+        let mut out = vec![];
+
+        Self::collect_matching(&self.content, &mut vec![], &pattern, &self.separator, &mut out);
+
+        for (path, value) in out.iter() {
+            self.record(path, Some(value));
+        }
+
+        out
+    }
+
+    pub fn get_all_str(&self, path: &str) -> Vec<(String, String)> {
+        self.get_all(path).into_iter()
+            .filter(|(_, value)| !value.is_mapping() && !value.is_sequence())
+            .map(|(path, value)| (path, Self::to_string(&value)))
+            .collect()
+    }
+
+    pub fn path_separator_convert(&self, path: &str, from_sep: &str) -> Option<Value> {
+        let value = Self::get_leaf(&self.content, path, from_sep);
+        self.record(path, value.as_ref());
+
+        value
+    }
+
+    pub fn translate_separator(&self, from: &str, to: &str) -> Config {
+        let mut config = self.detached();
+        config.separator = to.to_string();
+
+        // only values that resolve to an existing path are treated as paths
+        Self::update_strings(&mut config.content, &mut |v| {
+            if v.contains(from) && Self::get_leaf(&self.content, v, from).is_some() {
+                Some(v.replace(from, to))
+            } else {
+                None
+            }
+        });
+
+        config
     }
 
     pub fn str(&self, path: &str) -> String {
-        let content = Self::get_leaf(&self.content, path, &self.separator);
+        let content = self.get(path);
 
         match content {
             Some(v) => Self::to_string(&v),
@@ -51,7 +372,7 @@ impl Config {
         }
     }
     pub fn list(&self, path: &str) -> Vec<String> {        
-        let content = Self::get_leaf(&self.content, path, &self.separator);
+        let content = self.get(path);
         
         match content {
             Some(v) => Self::to_list(&v),
@@ -59,6 +380,103 @@ impl Config {
         }
     }
 
+    pub fn as_map_ref(&self, path: &str) -> Option<&Mapping> {
+        let value = Self::get_ref(&self.content, path, &self.separator);
+        self.record(path, value);
+
+        match value {
+            Some(Value::Mapping(v)) => Some(v),
+            _ => None
+        }
+    }
+
+    pub fn list_with_index(&self, path: &str) -> Vec<(usize, String)> {
+        self.list(path).into_iter().enumerate().collect()
+    }
+
+    pub fn get_typed_list<T: FromStr>(&self, path: &str) -> Vec<T> where T::Err: fmt::Debug {
+        self.list(path).iter().filter_map(|v| v.parse::<T>().ok()).collect()
+    }
+
+    pub fn list_sorted(&self, path: &str) -> Vec<String> {
+        let mut list = self.list(path);
+        list.sort();
+        list
+    }
+
+    pub fn list_sorted_by<F: Fn(&str, &str) -> std::cmp::Ordering>(&self, path: &str, cmp: F) -> Vec<String> {
+        let mut list = self.list(path);
+        list.sort_by(|a, b| cmp(a, b));
+        list
+    }
+
+    pub fn parse_duration(&self, path: &str) -> Option<Duration> {
+        let value = self.get(path)?;
+
+        Self::to_duration(Self::to_string(&value).trim())
+    }
+
+    pub fn parse_bytes(&self, path: &str) -> Option<u64> {
+        let value = self.get(path)?;
+
+        Self::to_bytes(Self::to_string(&value).trim())
+    }
+
+    pub fn parse_socket_addr(&self, path: &str) -> Option<SocketAddr> {
+        self.str(path).trim().parse::<SocketAddr>().ok()
+    }
+
+    pub fn build_socket_addr(&self, host_path: &str, port_path: &str) -> Option<SocketAddr> {
+        let host = self.str(host_path);
+        let host = host.trim().trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().ok()?;
+        let port = self.str(port_path).trim().parse::<u16>().ok()?;
+
+        Some(SocketAddr::new(host, port))
+    }
+
+    #[cfg(feature = "url")]
+    pub fn build_url(&self, scheme_path: &str, host_path: &str, port_path: &str, path_path: &str) -> Option<url::Url> {
+        let scheme = self.str(scheme_path);
+        let host = self.str(host_path);
+
+        if scheme.is_empty() || host.is_empty() {
+            return None;
+        }
+
+        let host = if host.contains(':') && !host.starts_with('[') {
+            format!("[{host}]")
+        } else {
+            host
+        };
+        let port = match self.str(port_path) {
+            v if v.is_empty() => match scheme.as_str() {
+                "http" | "ws" => Some(80),
+                "https" | "wss" => Some(443),
+                _ => None
+            },
+            v => Some(v.parse::<u16>().ok()?)
+        };
+        let path = match self.str(path_path) {
+            v if v.is_empty() => String::from("/"),
+            v if v.starts_with('/') => v,
+            v => format!("/{v}")
+        };
+
+        let url = match port {
+            Some(port) => format!("{scheme}://{host}:{port}{path}"),
+            None => format!("{scheme}://{host}{path}")
+        };
+
+        url::Url::parse(&url).ok()
+    }
+
+    pub fn map_at_typed<V: FromStr>(&self, path: &str) -> HashMap<String, V> where V::Err: fmt::Debug {
+        match self.get(path) {
+            Some(v) => Self::to_typed_map(&v),
+            None => HashMap::new()
+        }
+    }
+
     pub fn fmt(&self, format: &str, path: &str) -> String {
         let mut content = &self.content.clone();
         let mut parts = path.split(&self.separator).collect::<Vec<&str>>();
@@ -80,6 +498,7 @@ impl Config {
                 for item in attributes.iter() {
                     match content.get(item) {
                         Some(v) => {
+                            self.record(&parts.iter().chain([item]).cloned().collect::<Vec<&str>>().join(&self.separator), Some(v));
                             fmt = fmt.replacen("{}", &format!("{{{}}}", item), 1);
                             vars.insert(item.to_string(), Self::to_string(v));
                         },
@@ -87,59 +506,1364 @@ impl Config {
                     }
                 }
 
-                return match strfmt(&fmt, &vars) {
-                    Ok(r) => r,
-                    Err(_) => String::new()
-                };
+                strfmt(&fmt, &vars).unwrap_or_default()
             },
             None => String::new()
         }
     }
 
-    fn get_leaf(mut content: &Value, path: &str, separator: &str) -> Option<Value> {
-        let parts = path.split(separator).collect::<Vec<&str>>();
-    
-        for item in parts.iter() {
-            match content.get(item) {
-                Some(v) => { content = v; },
-                None => return None
+    pub fn fmt_multi(&self, format: &str, bindings: &[(&str, &str)]) -> String {
+        let mut vars = HashMap::new();
+
+        for (name, path) in bindings.iter() {
+            match self.get(path) {
+                Some(v) => { vars.insert(name.to_string(), Self::to_string(&v)); },
+                None => return String::new()
             }
         }
 
-        return Some(content.clone());
+        strfmt(format, &vars).unwrap_or_default()
     }
 
-    fn get_file(filename: &str, env: Option<&str>) -> (String, Option<String>) {
-        match env {
-            Some(v) => {
-                let mut vars = HashMap::new();
-                vars.insert(String::from("env"), v);
-                (strfmt(filename, &vars).unwrap(), Some(v.to_string()))
+    pub fn empty(sep: &str) -> Config {
+        Self::from_content(Value::Mapping(Mapping::new()), String::new(), sep, None)
+    }
+
+    pub fn merge_all<I: IntoIterator<Item = Config>>(configs: I, sep: &str) -> Config {
+        // an empty or non-mapping config has no keys to contribute and would otherwise replace the result
+        configs.into_iter().fold(Self::empty(sep), |mut merged, config| {
+            if config.content.is_mapping() {
+                Self::merge_value(&mut merged.content, &config.content);
+            }
+            merged
+        })
+    }
+
+    pub fn merge_lazy(configs: Vec<Config>) -> LazyMergedConfig {
+        LazyMergedConfig {
+            configs,
+            cache: DashMap::new()
+        }
+    }
+
+    pub fn merge_ordered(mut configs: Vec<(Config, u32)>, sep: &str) -> Config {
+        configs.sort_by_key(|(_, priority)| *priority);
+
+        Self::merge_all(configs.into_iter().map(|(config, _)| config), sep)
+    }
+
+    pub fn from_properties_string(props: &str, sep: &str) -> Result<Config, ConfigError> {
+        let mut content = Value::Mapping(Mapping::new());
+        let mut lines = props.lines().enumerate();
+
+        while let Some((number, line)) = lines.next() {
+            let mut logical = line.trim_start().to_string();
+
+            if logical.is_empty() || logical.starts_with('#') || logical.starts_with('!') {
+                continue;
+            }
+
+            while Self::is_continued(&logical) {
+                logical.pop();
+
+                match lines.next() {
+                    Some((_, next)) => logical.push_str(next.trim_start()),
+                    None => break
+                }
+            }
+
+            let split = Self::find_property_separator(&logical)
+                .ok_or_else(|| ConfigError::ParseError(format!("line {}: expected 'key=value' or 'key: value'", number + 1)))?;
+            let key = Self::unescape_property(Self::trim_unescaped_end(&logical[..split]));
+            let value = Self::unescape_property(logical[split + 1..].trim_start());
+
+            if key.is_empty() {
+                return Err(ConfigError::ParseError(format!("line {}: empty key", number + 1)));
+            }
+
+            Self::insert_path(&mut content, &key.split('.').collect::<Vec<&str>>(), Value::String(value));
+        }
+
+        Ok(Self::from_content(content, String::new(), sep, None))
+    }
+
+    pub fn from_hashmap_str(map: HashMap<String, String>, sep: &str) -> Config {
+        let entries = map.into_iter()
+            .map(|(key, value)| (key.split(sep).map(String::from).collect::<Vec<String>>(), value))
+            .collect::<Vec<(Vec<String>, String)>>();
+
+        // a key that is both a leaf and a parent of other keys has no consistent value
+        let conflicts = entries.iter()
+            .filter(|(key, _)| entries.iter().any(|(other, _)| other.len() > key.len() && other.starts_with(key)))
+            .map(|(key, _)| key)
+            .collect::<Vec<&Vec<String>>>();
+        let mut content = Value::Mapping(Mapping::new());
+
+        for (key, value) in entries.iter() {
+            if !conflicts.iter().any(|c| key.starts_with(c)) {
+                Self::insert_path(&mut content, key, Value::String(value.clone()));
+            }
+        }
+
+        for key in conflicts.iter() {
+            if !conflicts.iter().any(|c| c.len() < key.len() && key.starts_with(c)) {
+                Self::insert_path(&mut content, key, Value::Mapping(Mapping::new()));
+            }
+        }
+
+        Self::from_content(content, String::new(), sep, None)
+    }
+
+    #[cfg(feature = "json-interop")]
+    pub fn from_json_value(v: serde_json::Value, sep: &str) -> Config {
+        Self::from_content(Self::from_json(v), String::new(), sep, None)
+    }
+
+    pub fn merge_with_report(&self, other: &Config) -> (Config, Vec<MergeConflict>) {
+        let mut merged = self.detached();
+        let mut report = vec![];
+
+        // same rule as merge_all: an empty or non-mapping config contributes nothing
+        if !other.content.is_mapping() {
+            return (merged, report);
+        }
+
+        Self::collect_conflicts(&self.content, &other.content, &mut vec![], &self.separator, &mut report);
+        Self::merge_value(&mut merged.content, &other.content);
+
+        (merged, report)
+    }
+
+    pub fn apply_overrides_yaml(&mut self, yaml: &str) -> Result<(), ConfigError> {
+        let overrides: Value = from_str(yaml)?;
+
+        self.merge_overlay(&overrides, "override")
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn merge_toml_str(&mut self, toml: &str) -> Result<(), ConfigError> {
+        match toml::from_str::<toml::Value>(toml) {
+            Ok(v) => {
+                Self::merge_value(&mut self.content, &Self::from_toml(v));
+                Ok(())
             },
-            None => (String::from(filename), None)
+            Err(e) => Err(ConfigError::ParseError(e.to_string()))
         }
     }
 
-    fn load(filename: &str) -> Result<Value, Box<dyn Error>> {
-        let yaml = fs::read_to_string(filename)?;
-        let parsed = from_str(&yaml)?;
-        
-        Ok(parsed)
+    pub fn merge_if_exists(&mut self, filename: &str) -> Result<bool, ConfigError> {
+        match Self::load(filename) {
+            Ok(v) => {
+                self.merge_overlay(&v, &format!("'{filename}'"))?;
+                Ok(true)
+            },
+            Err(ConfigError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e)
+        }
     }
-    
-    fn to_string(value: &Value) -> String {
-        match value {
-            Value::String(v) => v.to_string(),
-            Value::Number(v) => v.to_string(),
-            Value::Bool(v) => v.to_string(),
-            _ => String::new()
+
+    pub fn conditional_include(&mut self, condition_path: &str, condition_value: &str, include_file: &str) -> Result<bool, ConfigError> {
+        if self.get(condition_path).is_none() || self.str(condition_path) != condition_value {
+            return Ok(false);
         }
+
+        let include = Self::load(include_file)?;
+        self.merge_overlay(&include, &format!("'{include_file}'"))?;
+
+        Ok(true)
     }
 
-    fn to_list(value: &Value) -> Vec<String> {
-        match value {
-            Value::Sequence(v) => v.iter().map(Self::to_string).collect::<Vec<String>>(),
-            _ => vec![]
+    pub fn migrate<'a>(&mut self, migrations: &[(&'a str, Migration)]) -> Result<Vec<&'a str>, ConfigError> {
+        let mut applied = self.list("_applied_migrations");
+        let mut ran = vec![];
+
+        // _config_version counts how many of the listed migrations the config has been through;
+        // once _applied_migrations has been written it is the record and _config_version is ignored
+        if self.get("_applied_migrations").is_none() {
+            let version = match self.get("_config_version") {
+                Some(v) => {
+                    let v = Self::to_string(&v);
+                    v.parse::<usize>().map_err(|_| ConfigError::ParseError(format!("_config_version '{v}' is not a migration count")))?
+                },
+                None => 0
+            };
+            applied = migrations.iter().take(version).map(|(name, _)| name.to_string()).collect();
+        }
+
+        for (name, migration) in migrations.iter() {
+            if applied.iter().any(|a| a == name) {
+                continue;
+            }
+
+            migration(self)?;
+            applied.push(name.to_string());
+            ran.push(*name);
+
+            // recorded after each step so a failed migration keeps earlier ones marked as applied
+            let list = Value::Sequence(applied.iter().map(|a| Value::String(a.clone())).collect());
+            Self::insert_path(&mut self.content, &["_applied_migrations"], list);
         }
+
+        Ok(ran)
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "json-interop")]
+    pub fn to_serde_json_value(&self) -> serde_json::Value {
+        Self::to_json(&self.content)
+    }
+
+    #[cfg(feature = "json-interop")]
+    pub fn to_json_lines(&self) -> Result<String, ConfigError> {
+        let map = match &self.content {
+            Value::Mapping(v) => v,
+            _ => return Err(ConfigError::FormatError(String::from("top-level value is not a mapping")))
+        };
+        let mut lines = String::new();
+
+        for (key, value) in map.iter() {
+            let mut object = serde_json::Map::new();
+            object.insert(Self::to_string(key), Self::to_json(value));
+
+            match serde_json::to_string(&object) {
+                Ok(line) => { lines.push_str(&line); lines.push('\n'); },
+                Err(e) => return Err(ConfigError::FormatError(e.to_string()))
+            }
+        }
+
+        Ok(lines)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn merge_from_toml_file(&mut self, filename: &str) -> Result<(), ConfigError> {
+        let (file, _) = if filename.contains("{env}") {
+            Self::get_file(filename, self.environment())
+        } else {
+            (filename.to_string(), None)
+        };
+        let toml = fs::read_to_string(file)?;
+
+        self.merge_toml_str(&toml)
+    }
+
+    pub fn interpolate_shell(&mut self) -> Result<(), ConfigError> {
+        // expanded into a copy so a failing command leaves the config untouched
+        let mut content = self.content.clone();
+
+        for (parts, value) in Self::leaves(&self.content) {
+            let text = match value {
+                Value::String(v) if v.contains('$') => v,
+                _ => continue
+            };
+
+            let expanded = Self::expand_shell(text, self.allow_process_substitution).map_err(|e| match e {
+                ConfigError::FormatError(e) => ConfigError::FormatError(format!("path '{}': {e}", parts.join(&self.separator))),
+                e => e
+            })?;
+
+            if let Some(leaf) = Self::leaf_mut(&mut content, &parts) {
+                *leaf = Value::String(expanded);
+            }
+        }
+
+        self.content = content;
+        Ok(())
+    }
+
+    pub fn merge_precedence_list(mut sources: Vec<ConfigSource>) -> (Config, Vec<MergeConflict>) {
+        sources.sort_by_key(|source| source.priority);
+
+        // the result keeps the separator of the lowest-priority source
+        let mut sources = sources.into_iter();
+        let mut merged = match sources.next() {
+            Some(base) => base.config,
+            None => return (Self::empty("/"), vec![])
+        };
+
+        if !merged.content.is_mapping() {
+            merged.content = Value::Mapping(Mapping::new());
+        }
+
+        let mut report = vec![];
+
+        for source in sources {
+            let (next, conflicts) = merged.merge_with_report(&source.config);
+            merged = next;
+
+            report.extend(conflicts.into_iter().map(|c| MergeConflict { label: Some(source.label.clone()), ..c }));
+        }
+
+        (merged, report)
+    }
+
+    pub fn diff_values(&self, other: &Config) -> ConfigDiff {
+        let ours = Self::leaves(&self.content);
+        let theirs = Self::leaves(&other.content);
+        let lookup = theirs.iter().cloned().collect::<HashMap<Vec<String>, &Value>>();
+        let mut diff = ConfigDiff::default();
+
+        for (parts, value) in ours.iter() {
+            match lookup.get(parts) {
+                Some(v) if v != value => diff.changed.push(parts.join(&self.separator)),
+                Some(_) => {},
+                None => diff.added.push(parts.join(&self.separator))
+            }
+        }
+
+        let lookup = ours.into_iter().collect::<HashMap<Vec<String>, &Value>>();
+
+        for (parts, _) in theirs.iter() {
+            if !lookup.contains_key(parts) {
+                diff.removed.push(parts.join(&self.separator));
+            }
+        }
+
+        diff
+    }
+
+    pub fn diff_from_file(&self) -> Result<ConfigDiff, ConfigError> {
+        if self.filename.is_empty() {
+            return Err(ConfigError::NotFound);
+        }
+
+        let disk = Self::from_content(Self::load(&self.filename)?, self.filename.clone(), &self.separator, self.environment.clone());
+
+        Ok(self.diff_values(&disk))
+    }
+
+    pub fn is_superset_of(&self, other: &Config) -> bool {
+        Self::leaves(&other.content).iter().all(|(parts, _)| Self::find(&self.content, parts).is_some())
+    }
+
+    pub fn is_subset_of(&self, other: &Config) -> bool {
+        other.is_superset_of(self)
+    }
+
+    pub fn validate_value_regex(&self, rules: &[(&str, &str)]) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+
+        for (path, pattern) in rules.iter() {
+            let value = match self.get(path) {
+                Some(v) => Self::to_string(&v),
+                None => {
+                    errors.push(format!("path '{path}' not found"));
+                    continue;
+                }
+            };
+
+            match Regex::new(pattern) {
+                Ok(re) if re.is_match(&value) => {},
+                Ok(_) => errors.push(format!("path '{path}': value '{value}' does not match pattern '{pattern}'")),
+                Err(e) => errors.push(format!("path '{path}': invalid pattern '{pattern}': {e}"))
+            }
+        }
+
+        Self::validation_result(errors)
+    }
+
+    pub fn validate_numeric_range(&self, rules: &[(&str, i64, i64)]) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+
+        for (path, min, max) in rules.iter() {
+            let value = match self.get(path) {
+                Some(v) => Self::to_string(&v),
+                None => {
+                    errors.push(format!("path '{path}' not found"));
+                    continue;
+                }
+            };
+
+            match value.parse::<i64>() {
+                Ok(v) if *min <= v && v <= *max => {},
+                Ok(v) => errors.push(format!("path '{path}': value {v} is outside range [{min}, {max}]")),
+                Err(_) => errors.push(format!("path '{path}': value '{value}' is not an integer"))
+            }
+        }
+
+        Self::validation_result(errors)
+    }
+
+    pub fn validate_no_empty_values(&self, paths: &[&str]) -> Result<(), Vec<String>> {
+        let failing = paths.iter()
+            .filter(|path| match self.get(path) {
+                Some(Value::Mapping(v)) => v.is_empty(),
+                Some(Value::Sequence(v)) => v.is_empty(),
+                Some(v) => Self::to_string(&v).is_empty(),
+                None => true
+            })
+            .map(|path| path.to_string())
+            .collect();
+
+        Self::validation_result(failing)
+    }
+
+    pub fn validate_keys_pattern(&self, pattern: &str) -> Result<(), Vec<String>> {
+        let re = match Regex::new(pattern) {
+            Ok(re) => re,
+            Err(e) => return Err(vec![format!("invalid pattern '{pattern}': {e}")])
+        };
+        let mut errors = vec![];
+
+        Self::check_keys(&self.content, &re, &mut vec![], &self.separator, &mut |key, path| {
+            errors.push(format!("key '{key}' at path '{path}' does not match pattern '{pattern}'"));
+        });
+
+        Self::validation_result(errors)
+    }
+
+    pub fn list_duplicates(&self) -> Vec<(String, Vec<String>)> {
+        let mut groups: Vec<(String, Vec<(String, &Value)>)> = vec![];
+
+        for (parts, value) in Self::leaves(&self.content) {
+            let (key, parent) = parts.split_last().unwrap();
+
+            // sequence indices are positions, not key names
+            if let Some(Value::Sequence(_)) = Self::find(&self.content, parent) {
+                continue;
+            }
+
+            let path = parts.join(&self.separator);
+
+            match groups.iter_mut().find(|(name, _)| name == key) {
+                Some((_, entries)) => entries.push((path, value)),
+                None => groups.push((key.clone(), vec![(path, value)]))
+            }
+        }
+
+        groups.into_iter()
+            .filter(|(_, entries)| entries.iter().any(|(_, value)| *value != entries[0].1))
+            .map(|(name, entries)| (name, entries.into_iter().map(|(path, _)| path).collect()))
+            .collect()
+    }
+
+    pub fn split_by_type(&self) -> HashMap<ValueType, Vec<String>> {
+        let mut types: HashMap<ValueType, Vec<String>> = HashMap::new();
+
+        for (parts, value) in Self::leaves(&self.content) {
+            types.entry(Self::value_type(value)).or_default().push(parts.join(&self.separator));
+        }
+
+        types
+    }
+
+    pub fn key_path_tree(&self) -> Vec<ConfigNode> {
+        Self::to_nodes(&self.content)
+    }
+
+    pub fn export_redacted(&self, secret_paths: &[&str]) -> Config {
+        let mut config = self.detached();
+        let patterns = secret_paths.iter()
+            .map(|p| p.split(&self.separator).collect::<Vec<&str>>())
+            .collect::<Vec<Vec<&str>>>();
+
+        Self::redact(&mut config.content, &mut vec![], &patterns);
+        config
+    }
+
+    pub fn flatten(&self) -> HashMap<String, String> {
+        self.flatten_with_sep(&self.separator)
+    }
+
+    pub fn flatten_with_sep(&self, flat_sep: &str) -> HashMap<String, String> {
+        self.flat_entries(flat_sep).into_iter().collect()
+    }
+
+    pub fn to_table(&self, redact_patterns: &[&str]) -> Vec<Vec<String>> {
+        let mut rows = self.export_redacted(redact_patterns).flatten().into_iter()
+            .map(|(path, value)| vec![path, value])
+            .collect::<Vec<Vec<String>>>();
+
+        rows.sort();
+        rows
+    }
+
+    pub fn to_properties_string(&self) -> String {
+        let mut entries = self.flat_entries(".");
+        entries.sort();
+
+        entries.iter()
+            .map(|(key, value)| format!("{}={}\n", Self::escape_property(key, true), Self::escape_property(value, false)))
+            .collect()
+    }
+
+    pub fn to_env_vars(&self, prefix: &str) -> Vec<(String, String)> {
+        Self::leaves(&self.content).iter()
+            .map(|(parts, value)| {
+                let mut name = parts.join("_").to_uppercase();
+
+                if !prefix.is_empty() {
+                    name = format!("{}_{}", prefix.to_uppercase(), name);
+                }
+
+                (name, Self::to_string(value))
+            })
+            .collect()
+    }
+
+    pub fn write_to_env(&self, prefix: &str) -> usize {
+        let mut count = 0;
+
+        for (key, value) in self.to_env_vars(prefix) {
+            if value.is_empty() {
+                continue;
+            }
+
+            // set_var panics on these, so they can only be reported
+            if key.is_empty() || key.contains('=') || key.contains('\0') || value.contains('\0') {
+                eprintln!("trail-config: skipping invalid environment variable '{key}'");
+                continue;
+            }
+
+            if !Self::is_portable_env_name(&key) {
+                eprintln!("trail-config: environment variable '{key}' contains characters that are not portable");
+            }
+
+            env::set_var(&key, &value);
+            count += 1;
+        }
+
+        count
+    }
+
+    pub fn apply_env_overrides(&mut self, prefix: &str) -> usize {
+        let paths = Self::leaves(&self.content).into_iter().map(|(parts, _)| parts).collect::<Vec<Vec<String>>>();
+        let mut count = 0;
+
+        for (parts, (name, _)) in paths.iter().zip(self.to_env_vars(prefix)) {
+            if let Ok(raw) = env::var(&name) {
+                if let Some(leaf) = Self::leaf_mut(&mut self.content, parts) {
+                    *leaf = Self::parse_scalar(&raw);
+                    self.override_sources.insert(parts.join(&self.separator), name);
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    pub fn list_env_overrides(&self) -> Vec<(String, String)> {
+        let mut overrides = self.override_sources.iter()
+            .map(|(path, name)| (path.clone(), name.clone()))
+            .collect::<Vec<(String, String)>>();
+
+        overrides.sort();
+        overrides
+    }
+
+    fn validation_result(errors: Vec<String>) -> Result<(), Vec<String>> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn is_portable_env_name(name: &str) -> bool {
+        let mut chars = name.chars();
+
+        match chars.next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            _ => false
+        }
+    }
+
+    fn flat_entries(&self, sep: &str) -> Vec<(String, String)> {
+        Self::leaves(&self.content).iter()
+            .map(|(parts, value)| (parts.join(sep), Self::to_string(value)))
+            .collect()
+    }
+
+    fn escape_property(text: &str, key: bool) -> String {
+        let mut escaped = String::with_capacity(text.len());
+
+        // readers strip leading whitespace from values and treat a leading '#' or '!' as a comment
+        let mut leading = true;
+
+        for (index, c) in text.chars().enumerate() {
+            leading = leading && c.is_whitespace();
+
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '=' | ':' | ' ' if key => { escaped.push('\\'); escaped.push(c); },
+                '#' | '!' if key && index == 0 => { escaped.push('\\'); escaped.push(c); },
+                _ if leading => { escaped.push('\\'); escaped.push(c); },
+                _ => escaped.push(c)
+            }
+        }
+
+        escaped
+    }
+
+    #[cfg(feature = "json-interop")]
+    fn from_json(value: serde_json::Value) -> Value {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(v) => Value::Bool(v),
+            serde_json::Value::Number(v) => match (v.as_u64(), v.as_i64(), v.as_f64()) {
+                (Some(n), _, _) => Value::Number(n.into()),
+                (_, Some(n), _) => Value::Number(n.into()),
+                (_, _, Some(n)) => Value::Number(n.into()),
+                _ => Value::Null
+            },
+            serde_json::Value::String(v) => Value::String(v),
+            serde_json::Value::Array(v) => Value::Sequence(v.into_iter().map(Self::from_json).collect()),
+            serde_json::Value::Object(v) => Value::Mapping(v.into_iter().map(|(k, v)| (Value::String(k), Self::from_json(v))).collect())
+        }
+    }
+
+    #[cfg(feature = "json-interop")]
+    fn to_json(value: &Value) -> serde_json::Value {
+        match value {
+            Value::Null => serde_json::Value::Null,
+            Value::Bool(v) => serde_json::Value::Bool(*v),
+            Value::Number(v) => match (v.as_u64(), v.as_i64(), v.as_f64()) {
+                (Some(n), _, _) => n.into(),
+                (_, Some(n), _) => n.into(),
+                (_, _, Some(n)) => serde_json::Number::from_f64(n).map(serde_json::Value::Number).unwrap_or(serde_json::Value::Null),
+                _ => serde_json::Value::Null
+            },
+            Value::String(v) => serde_json::Value::String(v.clone()),
+            Value::Sequence(v) => serde_json::Value::Array(v.iter().map(Self::to_json).collect()),
+            Value::Mapping(v) => serde_json::Value::Object(v.iter().map(|(k, v)| (Self::to_string(k), Self::to_json(v))).collect()),
+            Value::Tagged(v) => Self::to_json(&v.value)
+        }
+    }
+
+    #[cfg(feature = "toml")]
+    fn from_toml(value: toml::Value) -> Value {
+        match value {
+            toml::Value::String(v) => Value::String(v),
+            toml::Value::Integer(v) => Value::Number(v.into()),
+            toml::Value::Float(v) => Value::Number(v.into()),
+            toml::Value::Boolean(v) => Value::Bool(v),
+            toml::Value::Datetime(v) => Value::String(v.to_string()),
+            toml::Value::Array(v) => Value::Sequence(v.into_iter().map(Self::from_toml).collect()),
+            toml::Value::Table(v) => Value::Mapping(v.into_iter().map(|(k, v)| (Value::String(k), Self::from_toml(v))).collect())
+        }
+    }
+
+    fn is_continued(line: &str) -> bool {
+        line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+    }
+
+    // whitespace before the separator is padding unless it was escaped as part of the key
+    fn trim_unescaped_end(text: &str) -> &str {
+        let mut end = text.len();
+
+        for (index, c) in text.char_indices().rev() {
+            let escapes = text[..index].chars().rev().take_while(|&c| c == '\\').count();
+
+            if !c.is_whitespace() || escapes % 2 == 1 {
+                break;
+            }
+
+            end = index;
+        }
+
+        &text[..end]
+    }
+
+    fn find_property_separator(line: &str) -> Option<usize> {
+        let mut escaped = false;
+
+        for (index, c) in line.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '=' | ':' => return Some(index),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn unescape_property(text: &str) -> String {
+        let mut unescaped = String::with_capacity(text.len());
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => unescaped.push(other),
+                None => {}
+            }
+        }
+
+        unescaped
+    }
+
+    fn insert_path<S: AsRef<str>>(content: &mut Value, parts: &[S], value: Value) {
+        match parts.split_first() {
+            Some((first, rest)) => {
+                if !content.is_mapping() {
+                    *content = Value::Mapping(Mapping::new());
+                }
+
+                if let Value::Mapping(map) = content {
+                    let key = Value::String(first.as_ref().to_string());
+                    let child = map.entry(key).or_insert(Value::Null);
+                    Self::insert_path(child, rest, value);
+                }
+            },
+            None => { *content = value; }
+        }
+    }
+
+    fn leaf_mut<'a>(mut content: &'a mut Value, parts: &[String]) -> Option<&'a mut Value> {
+        for item in parts.iter() {
+            content = match content {
+                Value::Sequence(v) => v.get_mut(item.parse::<usize>().ok()?)?,
+                _ => content.get_mut(item.as_str())?
+            };
+        }
+
+        Some(content)
+    }
+
+    fn parse_scalar(raw: &str) -> Value {
+        match from_str::<Value>(raw) {
+            Ok(v @ Value::Bool(_)) | Ok(v @ Value::Number(_)) | Ok(v @ Value::String(_)) => v,
+            _ => Value::String(raw.to_string())
+        }
+    }
+
+    fn leaves(content: &Value) -> Vec<(Vec<String>, &Value)> {
+        let mut out = vec![];
+        Self::collect_leaves(content, &mut vec![], &mut out);
+        out
+    }
+
+    fn collect_leaves<'a>(value: &'a Value, prefix: &mut Vec<String>, out: &mut Vec<(Vec<String>, &'a Value)>) {
+        match value {
+            Value::Mapping(v) if !v.is_empty() => {
+                for (key, item) in v.iter() {
+                    prefix.push(Self::to_string(key));
+                    Self::collect_leaves(item, prefix, out);
+                    prefix.pop();
+                }
+            },
+            Value::Sequence(v) if !v.is_empty() => {
+                for (index, item) in v.iter().enumerate() {
+                    prefix.push(index.to_string());
+                    Self::collect_leaves(item, prefix, out);
+                    prefix.pop();
+                }
+            },
+            _ => {
+                if !prefix.is_empty() {
+                    out.push((prefix.clone(), value));
+                }
+            }
+        }
+    }
+
+    fn check_keys<F: FnMut(&str, &str)>(value: &Value, re: &Regex, prefix: &mut Vec<String>, sep: &str, report: &mut F) {
+        match value {
+            Value::Mapping(v) => {
+                for (key, item) in v.iter() {
+                    let key = Self::to_string(key);
+                    prefix.push(key.clone());
+
+                    if !re.is_match(&key) {
+                        report(&key, &prefix.join(sep));
+                    }
+
+                    Self::check_keys(item, re, prefix, sep, report);
+                    prefix.pop();
+                }
+            },
+            Value::Sequence(v) => {
+                for (index, item) in v.iter().enumerate() {
+                    prefix.push(index.to_string());
+                    Self::check_keys(item, re, prefix, sep, report);
+                    prefix.pop();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn redact(value: &mut Value, prefix: &mut Vec<String>, patterns: &[Vec<&str>]) {
+        if !prefix.is_empty() && patterns.iter().any(|p| Self::glob_match(p, prefix)) {
+            *value = Value::String(String::from("[REDACTED]"));
+            return;
+        }
+
+        match value {
+            Value::Mapping(v) => {
+                for (key, item) in v.iter_mut() {
+                    prefix.push(Self::to_string(key));
+                    Self::redact(item, prefix, patterns);
+                    prefix.pop();
+                }
+            },
+            Value::Sequence(v) => {
+                for (index, item) in v.iter_mut().enumerate() {
+                    prefix.push(index.to_string());
+                    Self::redact(item, prefix, patterns);
+                    prefix.pop();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn collect_matching(value: &Value, prefix: &mut Vec<String>, pattern: &[&str], sep: &str, out: &mut Vec<(String, Value)>) {
+        if !prefix.is_empty() && Self::glob_match(pattern, prefix) {
+            out.push((prefix.join(sep), value.clone()));
+        }
+
+        match value {
+            Value::Mapping(v) => {
+                for (key, item) in v.iter() {
+                    prefix.push(Self::to_string(key));
+                    Self::collect_matching(item, prefix, pattern, sep, out);
+                    prefix.pop();
+                }
+            },
+            Value::Sequence(v) => {
+                for (index, item) in v.iter().enumerate() {
+                    prefix.push(index.to_string());
+                    Self::collect_matching(item, prefix, pattern, sep, out);
+                    prefix.pop();
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn glob_match<S: AsRef<str>>(pattern: &[&str], parts: &[S]) -> bool {
+        match (pattern.split_first(), parts.split_first()) {
+            (None, None) => true,
+            (Some((&"**", rest)), _) => {
+                Self::glob_match(rest, parts) || (!parts.is_empty() && Self::glob_match(pattern, &parts[1..]))
+            },
+            (Some((segment, rest)), Some((part, remaining))) => {
+                Self::wildcard_match(segment.as_bytes(), part.as_ref().as_bytes()) && Self::glob_match(rest, remaining)
+            },
+            _ => false
+        }
+    }
+
+    fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern.split_first() {
+            Some((b'*', rest)) => (0..=text.len()).any(|i| Self::wildcard_match(rest, &text[i..])),
+            Some((c, rest)) => text.first() == Some(c) && Self::wildcard_match(rest, &text[1..]),
+            None => text.is_empty()
+        }
+    }
+
+    fn value_type(value: &Value) -> ValueType {
+        match value {
+            Value::Null => ValueType::Null,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Number(v) if v.is_f64() => ValueType::Float,
+            Value::Number(_) => ValueType::Integer,
+            Value::String(_) => ValueType::String,
+            Value::Sequence(_) => ValueType::Sequence,
+            Value::Mapping(_) => ValueType::Mapping,
+            Value::Tagged(v) => Self::value_type(&v.value)
+        }
+    }
+
+    fn to_nodes(value: &Value) -> Vec<ConfigNode> {
+        let node = |key: String, value: &Value| match value {
+            Value::Mapping(_) | Value::Sequence(_) => ConfigNode { key, value: None, children: Self::to_nodes(value) },
+            _ => ConfigNode { key, value: Some(Self::to_string(value)), children: vec![] }
+        };
+
+        match value {
+            Value::Mapping(v) => v.iter().map(|(k, v)| node(Self::to_string(k), v)).collect(),
+            Value::Sequence(v) => v.iter().enumerate().map(|(i, v)| node(i.to_string(), v)).collect(),
+            _ => vec![]
+        }
+    }
+
+    fn update_strings<F: FnMut(&str) -> Option<String>>(value: &mut Value, update: &mut F) {
+        match value {
+            Value::String(v) => {
+                if let Some(updated) = update(v) {
+                    *v = updated;
+                }
+            },
+            Value::Mapping(v) => v.iter_mut().for_each(|(_, item)| Self::update_strings(item, update)),
+            Value::Sequence(v) => v.iter_mut().for_each(|item| Self::update_strings(item, update)),
+            _ => {}
+        }
+    }
+
+    fn collect_conflicts(base: &Value, other: &Value, prefix: &mut Vec<String>, sep: &str, out: &mut Vec<MergeConflict>) {
+        let conflict = |path: String, old: &Value, new: &Value, conflict_type| MergeConflict {
+            path,
+            old_value: Self::describe(old),
+            new_value: Self::describe(new),
+            conflict_type,
+            label: None
+        };
+
+        match (base, other) {
+            (Value::Mapping(base), Value::Mapping(other)) => {
+                for (key, value) in other.iter() {
+                    prefix.push(Self::to_string(key));
+
+                    match base.get(key) {
+                        Some(existing) => Self::collect_conflicts(existing, value, prefix, sep, out),
+                        None => {
+                            let leaves = Self::leaves(value);
+
+                            // scalars and empty containers have no leaves of their own
+                            if leaves.is_empty() {
+                                out.push(conflict(prefix.join(sep), &Value::Null, value, MergeConflictType::Added));
+                            }
+
+                            for (parts, leaf) in leaves {
+                                let path = prefix.iter().chain(parts.iter()).cloned().collect::<Vec<String>>().join(sep);
+                                out.push(conflict(path, &Value::Null, leaf, MergeConflictType::Added));
+                            }
+                        }
+                    }
+
+                    prefix.pop();
+                }
+            },
+            (Value::Sequence(_), Value::Sequence(_)) => {
+                if base != other {
+                    out.push(conflict(prefix.join(sep), base, other, MergeConflictType::SequenceReplaced));
+                }
+            },
+            _ => {
+                if base != other {
+                    out.push(conflict(prefix.join(sep), base, other, MergeConflictType::Changed));
+                }
+            }
+        }
+    }
+
+    fn describe(value: &Value) -> String {
+        match value {
+            Value::Sequence(v) => format!("[{}]", v.iter().map(Self::describe).collect::<Vec<String>>().join(", ")),
+            Value::Mapping(v) => format!("{{{}}}", v.iter().map(|(k, v)| format!("{}: {}", Self::to_string(k), Self::describe(v))).collect::<Vec<String>>().join(", ")),
+            _ => Self::to_string(value)
+        }
+    }
+
+    // an empty document has nothing to merge; anything but a mapping would replace the whole config
+    fn merge_overlay(&mut self, overlay: &Value, source: &str) -> Result<(), ConfigError> {
+        match overlay {
+            Value::Null => Ok(()),
+            Value::Mapping(_) => {
+                Self::merge_value(&mut self.content, overlay);
+                Ok(())
+            },
+            _ => Err(ConfigError::ParseError(format!("{source} must be a mapping")))
+        }
+    }
+
+    fn merge_value(base: &mut Value, other: &Value) {
+        match (base, other) {
+            (Value::Mapping(base), Value::Mapping(other)) => {
+                for (key, value) in other.iter() {
+                    match base.get_mut(key) {
+                        Some(existing) => Self::merge_value(existing, value),
+                        None => { base.insert(key.clone(), value.clone()); }
+                    }
+                }
+            },
+            (base, other) => { *base = other.clone(); }
+        }
+    }
+
+    fn expand_shell(text: &str, allow_process: bool) -> Result<String, ConfigError> {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('$') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+
+            if rest.starts_with('(') && allow_process {
+                let end = Self::closing_paren(rest)
+                    .ok_or_else(|| ConfigError::FormatError(format!("unterminated command substitution in '{text}'")))?;
+                expanded.push_str(&Self::run_command(&rest[1..end])?);
+                rest = &rest[end + 1..];
+                continue;
+            }
+
+            let name_len = match rest.chars().next() {
+                Some(c) if c.is_ascii_alphabetic() || c == '_' => rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len()),
+                _ => 0
+            };
+
+            // unset variables and anything that isn't a variable name are kept literally
+            match (name_len > 0).then(|| env::var(&rest[..name_len])) {
+                Some(Ok(value)) => expanded.push_str(&value),
+                _ => { expanded.push('$'); expanded.push_str(&rest[..name_len]); }
+            }
+
+            rest = &rest[name_len..];
+        }
+
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    fn closing_paren(text: &str) -> Option<usize> {
+        let mut depth = 0;
+
+        for (index, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return Some(index);
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn run_command(command: &str) -> Result<String, ConfigError> {
+        let output = if cfg!(windows) {
+            Command::new("cmd").args(["/C", command]).output()
+        } else {
+            Command::new("sh").args(["-c", command]).output()
+        };
+
+        match output {
+            Ok(v) if v.status.success() => Ok(String::from_utf8_lossy(&v.stdout).trim_end_matches(['\r', '\n']).to_string()),
+            Ok(v) => Err(ConfigError::FormatError(format!("command '{command}' failed with {}", v.status))),
+            Err(e) => Err(ConfigError::FormatError(format!("command '{command}' could not be run: {e}")))
+        }
+    }
+
+    fn to_duration(text: &str) -> Option<Duration> {
+        if let Ok(secs) = text.parse::<u64>() {
+            return Some(Duration::from_secs(secs));
+        }
+
+        let mut millis: u64 = 0;
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let amount = rest[..digits].parse::<u64>().ok()?;
+            rest = &rest[digits..];
+
+            let unit = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+            let factor = match &rest[..unit] {
+                "ms" => 1,
+                "s" => 1_000,
+                "m" => 60_000,
+                "h" => 3_600_000,
+                "d" => 86_400_000,
+                _ => return None
+            };
+            rest = &rest[unit..];
+
+            millis = millis.checked_add(amount.checked_mul(factor)?)?;
+        }
+
+        if text.is_empty() {
+            None
+        } else {
+            Some(Duration::from_millis(millis))
+        }
+    }
+
+    fn to_bytes(text: &str) -> Option<u64> {
+        let digits = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+        let amount = text[..digits].parse::<u64>().ok()?;
+
+        let factor: u64 = match text[digits..].trim_start().to_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "tb" => 1_000_000_000_000,
+            "pb" => 1_000_000_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            "pib" => 1 << 50,
+            _ => return None
+        };
+
+        amount.checked_mul(factor)
+    }
+
+    // a derived config gets its own audit log so reads on it don't land in the original's
+    fn detached(&self) -> Config {
+        Config {
+            audit_log: self.audit_log.as_ref().map(|_| Arc::new(Mutex::new(vec![]))),
+            ..self.clone()
+        }
+    }
+
+    fn from_content(content: Value, filename: String, sep: &str, env: Option<String>) -> Config {
+        Config {
+            content,
+            filename,
+            separator: sep.to_string(),
+            environment: env,
+            override_sources: HashMap::new(),
+            allow_process_substitution: false,
+            audit_log: None,
+            last_checksum: None,
+            annotations: HashMap::new()
+        }
+    }
+
+    fn record(&self, path: &str, value: Option<&Value>) {
+        if let Some(mut log) = self.audit_log.as_ref().and_then(|log| log.lock().ok()) {
+            log.push((path.to_string(), value.map(Self::describe).unwrap_or_default()));
+        }
+    }
+
+    fn get_leaf(content: &Value, path: &str, separator: &str) -> Option<Value> {
+        Self::get_ref(content, path, separator).cloned()
+    }
+
+    fn get_ref<'a>(mut content: &'a Value, path: &str, separator: &str) -> Option<&'a Value> {
+        let parts = path.split(separator).collect::<Vec<&str>>();
+    
+        for item in parts.iter() {
+            match content.get(item) {
+                Some(v) => { content = v; },
+                None => return None
+            }
+        }
+
+        Some(content)
+    }
+
+    // unlike get_ref, also indexes sequences so paths produced by leaves() resolve
+    fn find<'a, S: AsRef<str>>(mut content: &'a Value, parts: &[S]) -> Option<&'a Value> {
+        for item in parts.iter() {
+            let next = match content {
+                Value::Sequence(v) => item.as_ref().parse::<usize>().ok().and_then(|i| v.get(i)),
+                _ => content.get(item.as_ref())
+            };
+
+            match next {
+                Some(v) => { content = v; },
+                None => return None
+            }
+        }
+
+        Some(content)
+    }
+
+    fn get_file(filename: &str, env: Option<&str>) -> (String, Option<String>) {
+        match env {
+            Some(v) => {
+                let mut vars = HashMap::new();
+                vars.insert(String::from("env"), v);
+                (strfmt(filename, &vars).unwrap(), Some(v.to_string()))
+            },
+            None => (String::from(filename), None)
+        }
+    }
+
+    fn load(filename: &str) -> Result<Value, ConfigError> {
+        Self::load_with_checksum(filename).map(|(yaml, _)| yaml)
+    }
+
+    fn load_with_checksum(filename: &str) -> Result<(Value, u64), ConfigError> {
+        let yaml = fs::read_to_string(filename)?;
+        let parsed = from_str(&yaml)?;
+
+        Ok((parsed, Self::checksum(&yaml)))
+    }
+
+    fn checksum(text: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        hasher.finish()
+    }
+    
+    fn to_string(value: &Value) -> String {
+        match value {
+            Value::String(v) => v.to_string(),
+            Value::Number(v) => v.to_string(),
+            Value::Bool(v) => v.to_string(),
+            _ => String::new()
+        }
+    }
+
+    fn to_list(value: &Value) -> Vec<String> {
+        match value {
+            Value::Sequence(v) => v.iter().map(Self::to_string).collect::<Vec<String>>(),
+            _ => vec![]
+        }
+    }
+
+    fn to_typed_map<V: FromStr>(value: &Value) -> HashMap<String, V> {
+        match value {
+            Value::Mapping(v) => v.iter()
+                .filter_map(|(key, value)| Self::to_string(value).parse::<V>().ok().map(|v| (Self::to_string(key), v)))
+                .collect(),
+            _ => HashMap::new()
+        }
+    }
+}
+
+impl LazyMergedConfig {
+    pub fn get(&self, path: &str) -> Option<Value> {
+        if let Some(cached) = self.cache.get(path) {
+            return cached.clone();
+        }
+
+        let value = self.configs.iter().rev().find_map(|config| config.get(path));
+        self.cache.insert(path.to_string(), value.clone());
+
+        value
+    }
+
+    pub fn str(&self, path: &str) -> String {
+        match self.get(path) {
+            Some(v) => Config::to_string(&v),
+            None => String::new()
+        }
+    }
+
+    pub fn list(&self, path: &str) -> Vec<String> {
+        match self.get(path) {
+            Some(v) => Config::to_list(&v),
+            None => vec![]
+        }
+    }
+
+    pub fn map_at_typed<V: FromStr>(&self, path: &str) -> HashMap<String, V> where V::Err: fmt::Debug {
+        match self.get(path) {
+            Some(v) => Config::to_typed_map(&v),
+            None => HashMap::new()
+        }
+    }
+
+    // attributes are resolved one by one, so each can come from a different config
+    pub fn fmt(&self, format: &str, path: &str) -> String {
+        let sep = match self.configs.last() {
+            Some(config) => config.separator.clone(),
+            None => return String::new()
+        };
+
+        let (parent, last) = match path.rsplit_once(sep.as_str()) {
+            Some((parent, last)) => (Some(parent), last),
+            None => (None, path)
+        };
+
+        let mut fmt = format.to_string();
+        let mut vars = HashMap::new();
+
+        for item in last.split('+') {
+            let full = match parent {
+                Some(v) => format!("{v}{sep}{item}"),
+                None => item.to_string()
+            };
+
+            match self.get(&full) {
+                Some(v) => {
+                    fmt = fmt.replacen("{}", &format!("{{{item}}}"), 1);
+                    vars.insert(item.to_string(), Config::to_string(&v));
+                },
+                None => return String::new()
+            }
+        }
+
+        strfmt(&fmt, &vars).unwrap_or_default()
+    }
+
+    pub fn path_separator_convert(&self, path: &str, from_sep: &str) -> Option<Value> {
+        self.configs.iter().rev().find_map(|config| config.path_separator_convert(path, from_sep))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn properties_continuation_lines() {
+        let config = Config::from_properties_string("list=one, \\\n    two, \\\n    three\nnext=4\n", "/").unwrap();
+
+        assert_eq!("one, two, three", config.str("list"));
+        assert_eq!("4", config.str("next"));
+    }
+
+    #[test]
+    fn properties_escaped_separators_in_keys() {
+        let config = Config::from_properties_string("a\\:b=1\nc\\=d: 2\ne\\ f = 3\n", "/").unwrap();
+
+        assert_eq!("1", config.str("a:b"));
+        assert_eq!("2", config.str("c=d"));
+        assert_eq!("3", config.str("e f"));
+    }
+
+    #[test]
+    fn properties_comments_and_nesting() {
+        let config = Config::from_properties_string("# comment\n! other\n\ndb.redis.port=6379\n", "/").unwrap();
+
+        assert_eq!("6379", config.str("db/redis/port"));
+    }
+
+    #[test]
+    fn properties_error_line_number() {
+        match Config::from_properties_string("a=1\n# comment\nbroken\n", "/") {
+            Err(ConfigError::ParseError(e)) => assert!(e.starts_with("line 3:"), "{}", e),
+            other => panic!("expected parse error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn properties_round_trip() {
+        let yaml = "db:\n  url: 'a=b:c'\n  note: \"  two\\nlines\\\\\"\n'#key': x\n'!bang': y\n'a ': z\n";
+        let config = Config::from_reader(yaml.as_bytes(), "/").unwrap();
+        let parsed = Config::from_properties_string(&config.to_properties_string(), "/").unwrap();
+
+        assert_eq!("a=b:c", parsed.str("db/url"));
+        assert_eq!("  two\nlines\\", parsed.str("db/note"));
+        assert_eq!("x", parsed.str("#key"));
+        assert_eq!("y", parsed.str("!bang"));
+        assert_eq!("z", parsed.str("a "));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(Some(Duration::from_secs(30)), Config::to_duration("30s"));
+        assert_eq!(Some(Duration::from_secs(5400)), Config::to_duration("1h30m"));
+        assert_eq!(Some(Duration::from_millis(500)), Config::to_duration("500ms"));
+        assert_eq!(Some(Duration::from_secs(45)), Config::to_duration("45"));
+        assert_eq!(Some(Duration::from_secs(172_800)), Config::to_duration("2d"));
+        assert_eq!(None, Config::to_duration(""));
+        assert_eq!(None, Config::to_duration("5x"));
+        assert_eq!(None, Config::to_duration("ms"));
+    }
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(Some(512_000_000), Config::to_bytes("512MB"));
+        assert_eq!(Some(262_144), Config::to_bytes("256KiB"));
+        assert_eq!(Some(1_048_576), Config::to_bytes("1 MiB"));
+        assert_eq!(Some(45), Config::to_bytes("45"));
+        assert_eq!(None, Config::to_bytes("MB"));
+        assert_eq!(None, Config::to_bytes("5x"));
+        assert_eq!(None, Config::to_bytes("99999999PB"));
+    }
+}
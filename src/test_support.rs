@@ -0,0 +1,9 @@
+use crate::Config;
+
+impl Config {
+    pub fn assert_equals(&self, other: &Config) {
+        if self != other {
+            panic!("configs are not equal (+ only in left, - only in right, ~ changed):\n{}", self.diff_values(other));
+        }
+    }
+}